@@ -3,296 +3,1563 @@ use crossterm::ExecutableCommand;
 use crossterm::cursor::{Hide, MoveTo};
 use crossterm::terminal::{Clear, ClearType};
 use serde::{Deserialize, Serialize};
-use std::fs;
-use std::path::PathBuf;
-use clap::{Parser, Subcommand};
-use terminal_size::{terminal_size, Width};
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::path::{Path, PathBuf};
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+use terminal_size::{terminal_size_of, Width};
 use std::io;
-use std::io::{stdout, Write};
-use prettytable::{Table, Row, Cell};
+use std::io::{stdout, IsTerminal, Write};
+use prettytable::{color, Table, Row, Cell};
 use prettytable::Attr; // for bold, italic, etc.
 use directories_next::ProjectDirs;
-use std::collections::HashSet;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 
 
-#[derive(Serialize, Deserialize, Debug)]
+const DATA_VERSION: u32 = 1;
+const DEFAULT_GRAPH_WIDTH: u16 = 80;
+const ASCII_RAMP: [char; 5] = ['.', '-', '+', '*', '#'];
+const ASCII_WEEKEND: char = '_';
+const TODAY_MARKER: char = '<';
+const WEEKDAY_LABELS: [&str; 7] = ["M", "T", "W", "T", "F", "S", "S"];
+const GRAPH_GUTTER: usize = 2;
+// Green first, so the merged graph looks the same as it always has
+const GRAPH_COLORS: [(u8, u8, u8); 6] = [(0, 255, 0), (0, 128, 255), (255, 64, 64), (255, 200, 0), (0, 255, 255), (255, 0, 255)];
+const SPARK_LEVELS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+const SPARKLINE_DAYS: i64 = 14;
+const RECENT_DONE: char = '█';
+const RECENT_MISSED: char = '·';
+const MAX_RANGE_DAYS: i64 = 366;
+const MILESTONES: [u32; 3] = [7, 30, 100];
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+enum Frequency {
+    #[default]
+    Daily,
+    TimesPerWeek(u32),
+}
+
+// Ordering is derived field by field, so keep `name` first to sort habits by name
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 struct Habit {
     name: String,
-    streak: u32,
+    streak: u32, // in weeks for TimesPerWeek habits
     history: Vec<String>, // store dates as YYYY-MM-DD
+    #[serde(default)]
+    frequency: Frequency,
+    #[serde(default)]
+    archived: bool,
+    #[serde(default)]
+    description: Option<String>,
+    #[serde(default)]
+    target: Option<u32>,
+    /// Completions per day, only for dates marked more than once
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    counts: BTreeMap<String, u32>,
+}
+
+impl Habit {
+    /// Names match ignoring surrounding whitespace, so "water " is "water"
+    fn is_named(&self, name: &str) -> bool {
+        self.name.trim() == name.trim()
+    }
+
+    fn streak_unit(&self) -> &'static str {
+        match self.frequency {
+            Frequency::Daily => "day",
+            Frequency::TimesPerWeek(_) => "week",
+        }
+    }
+
+    /// Streak for display, with a `w` suffix when it counts weeks
+    fn streak_label(&self) -> String {
+        self.format_streak(self.streak)
+    }
+
+    /// Any streak of this habit in its own unit, e.g. "5" or "2w"
+    fn format_streak(&self, streak: u32) -> String {
+        match self.frequency {
+            Frequency::Daily => streak.to_string(),
+            Frequency::TimesPerWeek(_) => format!("{}w", streak),
+        }
+    }
+
+    fn longest_streak(&self) -> u32 {
+        match self.frequency {
+            Frequency::Daily => longest_streak(&self.history),
+            Frequency::TimesPerWeek(times) => longest_weekly_streak(&self.history, times),
+        }
+    }
+
+    /// Keep history sorted and free of duplicate dates
+    fn normalize(&mut self) {
+        self.history.sort();
+        self.history.dedup();
+        let history = &self.history;
+        self.counts.retain(|date, count| *count > 1 && history.binary_search(date).is_ok());
+    }
+
+    /// How many times the habit was done on a marked date
+    fn count_on(&self, date: &str) -> u32 {
+        self.counts.get(date).copied().unwrap_or(1)
+    }
+}
+
+/// Top level of habits.json
+#[derive(Serialize, Deserialize, Debug)]
+struct Data {
+    version: u32,
+    habits: Vec<Habit>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Event {
+    timestamp: String,
+    command: String,
+    habit: String,
+    dates: Vec<String>,
+    /// Only set on add, so replay rebuilds the habit with the right frequency
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    frequency: Option<Frequency>,
+    /// Only set on add, for habits created with a target
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    target: Option<u32>,
+    /// Set on add and describe
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    /// Only set on undo, the habits it restored
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    snapshot: Option<Vec<Habit>>,
 }
 
 #[derive(Parser)] 
 #[command(
     name = "rhabits",
     about = "A simple visual habit tracker",
-    override_usage = "rhabits <COMMAND> [HABIT] [DATE] \nSpecify the date in YYYY-MM-DD format. Multiple dates should be separated with spaces only.\nIf you accidentally use a wrong format or separator undo your actions with unmark command and the same arguments as previously.\nHabits are stored at $XDG_DATA_HOME/rhabits/habits.json"
+    override_usage = "rhabits <COMMAND> [HABIT] [DATE] \nSpecify the date in YYYY-MM-DD format, or as today, yesterday or a day offset like -1. Multiple dates should be separated with spaces only.\nIf you accidentally use a wrong format or separator run the undo command to restore the data from before your last change.\nHabits are stored at $XDG_DATA_HOME/rhabits/habits.json unless --data-dir or RHABITS_DATA_DIR is set\nSet RHABITS_EVENT_LOG=1 to also append every change to events.jsonl next to it"
 )]
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+    /// Directory holding habits.json, overrides RHABITS_DATA_DIR
+    #[arg(long, global = true)]
+    data_dir: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum SortBy {
+    Name,
+    Streak,
+    Recent,
+}
+
+#[derive(Args)]
+struct ListOptions {
+    /// Show how the streak built up over the last two weeks
+    #[arg(long)]
+    sparkline_streak: bool,
+    /// Include archived habits
+    #[arg(long)]
+    all: bool,
+    /// Order of the rows
+    #[arg(long, value_enum, default_value_t = SortBy::Streak)]
+    sort: SortBy,
+    /// Days shown in the recent column, up to a year, 0 to hide it
+    #[arg(long, default_value_t = SPARKLINE_DAYS as u32, value_parser = clap::value_parser!(u32).range(0..=MAX_RANGE_DAYS))]
+    days: u32,
+}
+
+#[derive(Args)]
+struct GraphOptions {
+    /// Width in columns to draw, instead of detecting the terminal width
+    #[arg(long)]
+    width: Option<u16>,
+    /// Upper limit on the width, for very wide terminals
+    #[arg(long)]
+    max_width: Option<u16>,
+    /// Number of weeks to draw, ending with the current one
+    #[arg(long, value_parser = clap::value_parser!(u16).range(1..))]
+    weeks: Option<u16>,
+    /// First day to draw, overrides --weeks and the width
+    #[arg(long, allow_negative_numbers = true)]
+    from: Option<String>,
+    /// Last day to draw, today if omitted
+    #[arg(long, allow_negative_numbers = true)]
+    to: Option<String>,
+    /// Don't shade the Saturday and Sunday rows
+    #[arg(long)]
+    no_weekend_shade: bool,
+    /// Don't mark today's cell
+    #[arg(long)]
+    no_today_marker: bool,
+    /// Color a day fully if any of the habits was done, instead of by how many
+    #[arg(long)]
+    any: bool,
+    /// Draw a separate, labeled grid for each habit
+    #[arg(long)]
+    separate: bool,
+    /// Include archived habits
+    #[arg(long)]
+    all: bool,
 }
 
 #[derive(Subcommand)]
 enum Commands {
     /// List all habits
-    List,
+    List {
+        #[command(flatten)]
+        options: ListOptions,
+    },
     /// Print the graph with your habit's history
     Graph {
         names: Vec<String>,
+        #[command(flatten)]
+        options: GraphOptions,
     },
     /// Mark a day (or days) as done, leave empty to mark today
     Mark {
         /// Name of the habit
         name: String,
+        /// Dates as YYYY-MM-DD, today, yesterday, an offset like -1, or START..END; add :N to count N times.
+        /// Offsets inside a range or with :N need -- first, e.g. `mark x -- -5..-3`
+        #[arg(allow_negative_numbers = true)]
         dates: Vec<String>,
+        /// Print nothing if every date is already marked
+        #[arg(long)]
+        quiet_if_exists: bool,
     },
     /// Unmark marked day (or days), leave empty to unmark today 
     Unmark {
         /// Name of the habit
         name: String,
+        /// Dates or START..END ranges, with -- first for ranges of offsets like -5..-3
+        #[arg(allow_negative_numbers = true)]
         dates: Vec<String>,
+        /// Don't ask before unmarking a range
+        #[arg(short, long)]
+        force: bool,
     },
     /// Add a new habit
     Add {
+        /// Names of the habits
+        #[arg(required = true)]
+        names: Vec<String>,
+        /// Count the streak in weeks with at least this many days done
+        #[arg(long, value_parser = clap::value_parser!(u32).range(1..=7))]
+        times_per_week: Option<u32>,
+        /// Short note on what the habit is for
+        #[arg(long)]
+        desc: Option<String>,
+        /// Streak to celebrate reaching, on top of the usual milestones
+        #[arg(long)]
+        target: Option<u32>,
+    },
+    /// Set or update a habit's description
+    Describe {
         /// Name of the habit
         name: String,
+        text: String,
     },
     /// Remove a habit
     Remove {
         name: String,
+        /// Don't ask for confirmation
+        #[arg(short, long)]
+        force: bool,
+    },
+    /// Show which habits are done today and which are still pending
+    Today,
+    /// Show totals, streaks and recent completion rate for a habit
+    Stats {
+        /// Name of the habit
+        name: String,
+    },
+    /// Delete history older than a date, for one habit or all of them
+    Purge {
+        /// Name of the habit, all habits if omitted
+        name: Option<String>,
+        /// Keep entries on or after this date
+        #[arg(long, allow_negative_numbers = true)]
+        before: String,
+        /// Don't ask for confirmation
+        #[arg(short, long)]
+        force: bool,
+    },
+    /// Hide a habit from list and graph without deleting its history
+    Archive {
+        name: String,
+    },
+    /// Bring an archived habit back
+    Unarchive {
+        name: String,
+    },
+    /// Recompute streaks from history without changing it
+    Recompute,
+    /// Forget a single entry from a habit's history
+    Forget {
+        /// Name of the habit
+        name: String,
+        date: String,
+    },
+    /// Rebuild habits.json from the events.jsonl log
+    Replay {
+        /// Don't ask before replacing the current data
+        #[arg(short, long)]
+        force: bool,
+    },
+    /// Restore the data from before the last change
+    Undo,
+    /// Print a shell completion script
+    #[command(hide = true)]
+    Completions {
+        shell: Shell,
+    },
+    /// Print a habit's streak as JSON for a Waybar custom module
+    Waybar {
+        /// Name of the habit
+        name: String,
+    },
+    /// Print a short done/total segment for a shell prompt
+    Prompt {
+        /// Wrap escape codes for zsh prompts
+        #[arg(long, conflicts_with = "bash")]
+        zsh: bool,
+        /// Wrap escape codes for bash prompts
+        #[arg(long)]
+        bash: bool,
+    },
+    /// Write every marked day as habit,date CSV
+    Export {
+        /// File to write, stdout if omitted
+        path: Option<PathBuf>,
+    },
+    /// Merge marked days from a habit,date CSV
+    Import {
+        path: PathBuf,
+    },
+    /// Show how another habits file differs from the current data
+    Diff {
+        /// Path to the other habits.json, e.g. a backup
+        path: PathBuf,
     },
 }
 
-fn unique_preserve_order(vec: &mut Vec<String>) {
-    let mut seen = HashSet::new();
-    vec.retain(|item| seen.insert(item.clone()));
-}
-
-fn get_habits_path() -> io::Result<PathBuf> {
+fn get_habits_path(data_dir: Option<PathBuf>) -> io::Result<PathBuf> {
     
-    let proj_dirs = ProjectDirs::from("", "w4shington-irving", "rhabits")
-        .expect("Failed to get project directories");
-
-    let data_dir = proj_dirs.data_dir();    // ~/.local/share/rhabits/
+    let data_dir = match data_dir.or_else(|| env::var_os("RHABITS_DATA_DIR").map(PathBuf::from)) {
+        Some(dir) => dir,
+        None => {
+            let proj_dirs = ProjectDirs::from("", "w4shington-irving", "rhabits")
+                .expect("Failed to get project directories");
+            proj_dirs.data_dir().to_path_buf()    // ~/.local/share/rhabits/
+        }
+    };
     let file_path = data_dir.join("habits.json");
 
     
     if !data_dir.exists() {
-        fs::create_dir_all(data_dir)?;
+        fs::create_dir_all(&data_dir)?;
     }
 
-    
-    if !file_path.exists() {
-        fs::write(&file_path, "[]")?; // start with empty array
-    }
+
+    // habits.json isn't created up front: load_data treats a missing file as an empty store,
+    // and the first save_data renames a complete file into place, so a concurrent run never
+    // sees a half-written one
 
     Ok(file_path)
 }
 
+/// Parses a habits file without touching the disk, upgrading the legacy bare array
+fn parse_data(contents: &str) -> serde_json::Result<Data> {
+    let value = serde_json::from_str::<serde_json::Value>(contents)?;
+    if value.is_array() {
+        // Files from before the version field are a bare array of habits
+        let habits = serde_json::from_value::<Vec<Habit>>(value)?;
+        Ok(Data { version: DATA_VERSION, habits })
+    } else {
+        serde_json::from_value::<Data>(value)
+    }
+}
+
 fn load_data(habits_path: &PathBuf) -> io::Result<Vec<Habit>> {
-    if let Ok(contents) = fs::read_to_string(habits_path) {
-        let habits: Vec<Habit> = serde_json::from_str(&contents).unwrap_or_default();
+    let contents = match fs::read_to_string(habits_path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => {
+            // Unreadable (bad UTF-8, permissions, ...) is not the same as empty, so refuse
+            // to go on rather than let the next save replace the file
+            let corrupt_path = habits_path.with_extension("json.corrupt");
+            if fs::copy(habits_path, &corrupt_path).is_ok() {
+                eprintln!("Warning: a copy of {} was kept at {}", habits_path.display(), corrupt_path.display());
+            }
+            return Err(e);
+        }
+    };
+
+    // An empty file is a fresh store (e.g. created by hand), not a corrupt one
+    if !contents.trim().is_empty() {
+        let parsed = parse_data(&contents).map(|data| {
+            if data.version > DATA_VERSION {
+                eprintln!("Warning: {} was written by a newer version of rhabits", habits_path.display());
+            }
+            data.habits
+        });
+        let mut habits: Vec<Habit> = match parsed {
+            Ok(habits) => habits,
+            Err(e) => {
+                // Keep a copy so the next save doesn't throw the data away for good
+                let corrupt_path = habits_path.with_extension("json.corrupt");
+                let _ = fs::copy(habits_path, &corrupt_path);
+                eprintln!("Warning: couldn't parse {} ({}), starting empty. A copy was kept at {}",
+                    habits_path.display(), e, corrupt_path.display());
+                Vec::new()
+            }
+        };
+        for habit in &mut habits {
+            habit.normalize();
+        }
         Ok(habits)
     } else {
         Ok(Vec::new())
     }
 }
 
-fn save_data(habits_path: &PathBuf, habits: &Vec<Habit>) -> io::Result<()> {
-    let json = serde_json::to_string_pretty(habits).unwrap();
-    fs::write(habits_path, json)
+fn save_data(habits_path: &PathBuf, habits: &[Habit]) -> io::Result<()> {
+    let data = Data { version: DATA_VERSION, habits: habits.to_vec() };
+    let json = serde_json::to_string_pretty(&data).unwrap();
+
+    // Nothing changed, so keep the backup from the last real change for undo
+    let previous = fs::read_to_string(habits_path).ok();
+    if previous.as_deref() == Some(json.as_str()) {
+        return Ok(());
+    }
+    // Only a change the user made is worth undoing. Recomputing a stale streak (e.g. on list)
+    // would otherwise replace the backup of their last real change
+    if let Some(previous) = previous {
+        let without_streaks = |habits: &[Habit]| -> Vec<Habit> {
+            habits.iter().map(|h| Habit { streak: 0, ..h.clone() }).collect()
+        };
+        let user_change = parse_data(&previous)
+            .map_or(true, |data| without_streaks(&data.habits) != without_streaks(habits));
+        if user_change {
+            // Best effort: failing to keep a backup mustn't also lose the change being saved
+            let backup_path = habits_path.with_extension("json.bak");
+            if let Err(e) = fs::write(&backup_path, previous) {
+                eprintln!("Warning: couldn't write {}: {}", backup_path.display(), e);
+            }
+        }
+    }
+
+    // Write next to the real file and rename over it, so a crash mid-write
+    // leaves the old habits.json intact instead of a truncated one
+    let tmp_path = habits_path.with_extension(format!("json.{}.tmp", std::process::id()));
+    let mut file = fs::File::create(&tmp_path)?;
+    file.write_all(json.as_bytes())?;
+    file.sync_all()?;
+    fs::rename(&tmp_path, habits_path)
+}
+
+fn current_streak(history: &[String], today: NaiveDate) -> u32 {
+    // Walk distinct dates so a repeated entry can't break the consecutive-day math
+    let dates: BTreeSet<NaiveDate> = history.iter()
+        .filter_map(|entry| NaiveDate::parse_from_str(entry.as_str(), "%Y-%m-%d").ok())
+        .collect();
+
+    // Today not being marked yet doesn't break the streak, so start from yesterday
+    let mut expected = if dates.contains(&today) { today } else { today - Duration::days(1) };
+    let mut streak = 0;
+
+    while dates.contains(&expected) {
+        streak += 1;
+        expected -= Duration::days(1);
+    }
+    streak
+}
+
+fn week_start(date: NaiveDate) -> NaiveDate {
+    date - Duration::days(date.weekday().num_days_from_monday() as i64)
+}
+
+/// Number of consecutive weeks, Monday to Sunday, with at least `times` days marked
+fn weekly_streak(history: &[String], today: NaiveDate, times: u32) -> u32 {
+    let dates: BTreeSet<NaiveDate> = history.iter()
+        .filter_map(|entry| NaiveDate::parse_from_str(entry.as_str(), "%Y-%m-%d").ok())
+        .collect();
+
+    let mut per_week: BTreeMap<NaiveDate, u32> = BTreeMap::new();
+    for date in dates {
+        *per_week.entry(week_start(date)).or_insert(0) += 1;
+    }
+    let kept = |week: NaiveDate| per_week.get(&week).copied().unwrap_or(0) >= times;
+
+    // The current week is still in progress, so it only counts once it's kept
+    let mut week = week_start(today);
+    let mut streak = 0;
+    if kept(week) {
+        streak += 1;
+    }
+    week -= Duration::days(7);
+
+    while kept(week) {
+        streak += 1;
+        week -= Duration::days(7);
+    }
+    streak
+}
+
+fn longest_streak(history: &[String]) -> u32 {
+    let dates: BTreeSet<NaiveDate> = history.iter()
+        .filter_map(|entry| NaiveDate::parse_from_str(entry.as_str(), "%Y-%m-%d").ok())
+        .collect();
+
+    let mut longest = 0;
+    let mut run = 0;
+    let mut previous: Option<NaiveDate> = None;
+
+    for &date in &dates {
+        if previous.is_some_and(|p| date - p == Duration::days(1)) {
+            run += 1;
+        } else {
+            run = 1;
+        }
+        longest = longest.max(run);
+        previous = Some(date);
+    }
+    longest
+}
+
+/// Longest run of consecutive weeks with at least `times` days marked
+fn longest_weekly_streak(history: &[String], times: u32) -> u32 {
+    let dates: BTreeSet<NaiveDate> = history.iter()
+        .filter_map(|entry| NaiveDate::parse_from_str(entry.as_str(), "%Y-%m-%d").ok())
+        .collect();
+
+    let mut per_week: BTreeMap<NaiveDate, u32> = BTreeMap::new();
+    for date in dates {
+        *per_week.entry(week_start(date)).or_insert(0) += 1;
+    }
+
+    let mut longest = 0;
+    let mut run = 0;
+    let mut previous: Option<NaiveDate> = None;
+
+    for (&week, _) in per_week.iter().filter(|(_, &days)| days >= times) {
+        if previous.is_some_and(|p| week - p == Duration::days(7)) {
+            run += 1;
+        } else {
+            run = 1;
+        }
+        longest = longest.max(run);
+        previous = Some(week);
+    }
+    longest
 }
 
 fn check_streak(habits: &mut Vec<Habit>) {
     let today = Local::now().date_naive();
     
     for habit in habits {
-        unique_preserve_order(&mut habit.history);
-        let mut previous_date = today + Duration::days(1);
-        let mut streak = 0;
-        
-        for entry in habit.history.iter().rev() {
-            let date = NaiveDate::parse_from_str(&entry.as_str(), "%Y-%m-%d").unwrap();
-            if previous_date - date == Duration::days(1) {
-                streak+=1;
-                previous_date = date.clone();
-                
-            } else {
-                //break;
+        habit.normalize();
+        habit.streak = match habit.frequency {
+            Frequency::Daily => current_streak(&habit.history, today),
+            Frequency::TimesPerWeek(times) => weekly_streak(&habit.history, today, times),
+        };
+    }
+}
+
+/// Parses YYYY-MM-DD, `today`, `yesterday` or a day offset like `-1`
+fn parse_date(input: &str) -> Option<NaiveDate> {
+    let today = Local::now().date_naive();
+    match input {
+        "today" => Some(today),
+        "yesterday" => Some(today - Duration::days(1)),
+        _ if input.starts_with(['-', '+']) => {
+            input.parse::<i64>().ok()
+                .and_then(Duration::try_days)
+                .and_then(|offset| today.checked_add_signed(offset))
+        }
+        _ => NaiveDate::parse_from_str(input, "%Y-%m-%d").ok(),
+    }
+}
+
+/// Drops the :N count from a DATE:N entry, since unmarking removes the whole day
+fn strip_count(entry: &str) -> &str {
+    entry.rsplit_once(':').map_or(entry, |(date, _)| date)
+}
+
+/// Canonical YYYY-MM-DD for anything parse_date understands, the input unchanged otherwise
+fn resolve_date(input: &str) -> String {
+    if let Some((date, count)) = input.rsplit_once(':') {
+        if let Some(date) = parse_date(date) {
+            return format!("{}:{}", date, count);
+        }
+    }
+    parse_date(input).map(|d| d.to_string()).unwrap_or_else(|| input.to_string())
+}
+
+/// Validates and appends dates and ranges to history, returning how many
+/// were accepted and how many were rejected as malformed
+fn push_dates(habit: &mut Habit, dates: &[String]) -> (usize, usize) {
+    let mut accepted = 0;
+    let mut rejected = 0;
+
+    for date in dates {
+        if date.contains("..") {
+            // Only marking is capped, so a typo can't fill in years of history.
+            // Unmarking a long range is allowed since it asks first
+            let capped = parse_range(date).and_then(|(start, end)| {
+                if (end - start).num_days() >= MAX_RANGE_DAYS {
+                    Err(format!("Range is longer than {} days: {}", MAX_RANGE_DAYS, date))
+                } else {
+                    Ok(())
+                }
+            });
+            match capped.and_then(|()| expand_range(date)) {
+                Ok(range) => {
+                    accepted += range.len();
+                    for date in range {
+                        if !habit.history.contains(&date) {
+                            habit.history.push(date);
+                        }
+                    }
+                }
+                Err(e) => {
+                    println!("{}", e);
+                    rejected += 1;
+                }
+            }
+            continue;
+        }
+        // DATE:N records N completions on that day
+        let (date, count) = match date.rsplit_once(':') {
+            Some((day, count)) => match count.parse::<u32>() {
+                Ok(count) if count > 0 => (day, Some(count)),
+                _ => {
+                    println!("Skipping {}: count must be a positive number", date);
+                    rejected += 1;
+                    continue;
+                }
+            },
+            None => (date.as_str(), None),
+        };
+        match parse_date(date) {
+            Some(date) => {
+                let date = date.to_string();
+                if let Some(count) = count {
+                    habit.counts.insert(date.clone(), count);
+                }
+                if !habit.history.contains(&date) {
+                    habit.history.push(date);
+                }
+                accepted += 1;
+            }
+            None => {
+                println!("Skipping {}: not a valid date", date);
+                rejected += 1;
             }
         }
-        habit.streak = streak; 
     }
+    habit.normalize();
+    (accepted, rejected)
+}
+
+/// Whether marking `entry` (a date, START..END or DATE:N) would change nothing
+fn already_marked(habit: &Habit, entry: &str) -> bool {
+    if entry.contains("..") {
+        return expand_range(entry).is_ok_and(|range| range.iter().all(|date| habit.history.contains(date)));
+    }
+    if let Some((date, count)) = entry.rsplit_once(':') {
+        return match (parse_date(date), count.parse::<u32>()) {
+            (Some(date), Ok(count)) => {
+                let date = date.to_string();
+                habit.history.contains(&date) && habit.count_on(&date) == count
+            }
+            _ => false,
+        };
+    }
+    habit.history.contains(&resolve_date(entry))
 }
 
-fn mark_habit(habits: &mut Vec<Habit>, name: &str, dates: Vec<String>) {
+/// Returns how many dates were accepted and how many were rejected as malformed
+fn mark_habit(habits: &mut [Habit], name: &str, dates: Vec<String>, quiet_if_exists: bool) -> (usize, usize) {
+    let mut accepted = 0;
+    let mut rejected = 0;
     
-    if let Some(habit) = habits.iter_mut().find(|h| h.name == name) {
+    if let Some(habit) = habits.iter_mut().find(|h| h.is_named(name)) {
+
+        if quiet_if_exists {
+            let today = Local::now().date_naive().to_string();
+            let nothing_to_do = if dates.is_empty() {
+                habit.history.contains(&today)
+            } else {
+                dates.iter().all(|d| already_marked(habit, d))
+            };
+            if nothing_to_do {
+                return (accepted, rejected);
+            }
+        }
         
         if dates.is_empty() {
             
             println!("Marking today as done!");
-            let current_date = Local::now().date_naive();
+            let current_date = Local::now().date_naive().to_string();
             
-            if let Some(last_entry) = habit.history.last() {
-                let date = NaiveDate::parse_from_str(&last_entry.as_str(), "%Y-%m-%d").unwrap();
-                if  date != current_date {
-                    habit.history.push(current_date.to_string());
-                    habit.streak+=1;
-                }
+            // check_streak recomputes the streak afterwards
+            if !habit.history.contains(&current_date) {
+                habit.history.push(current_date);
             }
+            accepted += 1;
 
         } else {
             println!("Marking: {:?}", dates);
-            habit.history.extend(dates.iter().cloned());
+            (accepted, rejected) = push_dates(habit, &dates);
         }
 
-        habit.history.sort();
+        habit.normalize();
     } else {
         println!("Habit not found.");
     }
+
+    (accepted, rejected)
+}
+
+fn parse_range(range: &str) -> Result<(NaiveDate, NaiveDate), String> {
+    let (start, end) = range.split_once("..").ok_or(format!("Invalid range: {}", range))?;
+    let start = parse_date(start).ok_or(format!("Invalid date: {}", start))?;
+    let end = parse_date(end).ok_or(format!("Invalid date: {}", end))?;
+
+    if end < start {
+        return Err(format!("Range ends before it starts: {}", range));
+    }
+    Ok((start, end))
+}
+
+fn expand_range(range: &str) -> Result<Vec<String>, String> {
+    let (start, end) = parse_range(range)?;
+
+    let mut dates = Vec::new();
+    let mut date = start;
+    while date <= end {
+        dates.push(date.to_string());
+        date += Duration::days(1);
+    }
+    Ok(dates)
+}
+
+fn confirm(prompt: &str) -> bool {
+    // Piped input can't answer for the user, so never treat it as a yes
+    if !io::stdin().is_terminal() {
+        println!("{} Refusing without a terminal, pass --force to skip the prompt.", prompt);
+        return false;
+    }
+    print!("{} [y/N] ", prompt);
+    stdout().flush().unwrap();
+
+    let mut answer = String::new();
+    if io::stdin().read_line(&mut answer).is_err() {
+        return false;
+    }
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+/// Celebrates milestones the streak just went past, so each is only announced once
+fn announce_milestones(habit: &Habit, previous: u32) {
+    let crossed = |milestone: u32| previous < milestone && habit.streak >= milestone;
+
+    for milestone in MILESTONES {
+        if crossed(milestone) && habit.target != Some(milestone) {
+            println!("🎉 {} hit a {} {} streak!", habit.name, milestone, habit.streak_unit());
+        }
+    }
+    if let Some(target) = habit.target.filter(|&target| crossed(target)) {
+        println!("🎯 {} reached its target of {} {}s!", habit.name, target, habit.streak_unit());
+    }
+}
+
+/// Drops entries strictly older than `before`, for one habit or all of them
+fn purge_before(habits: &mut [Habit], name: Option<&str>, before: NaiveDate) -> Vec<(String, usize)> {
+    let mut removed = Vec::new();
+    for habit in habits.iter_mut().filter(|h| name.is_none_or(|n| h.is_named(n))) {
+        let count = habit.history.len();
+        habit.history.retain(|entry| {
+            NaiveDate::parse_from_str(entry, "%Y-%m-%d").map_or(true, |date| date >= before)
+        });
+        removed.push((habit.name.clone(), count - habit.history.len()));
+    }
+    removed
 }
 
-fn unmark_habit(habits: &mut Vec<Habit>, name: &str, dates: Vec<String>) {
+fn unmark_habit(habits: &mut [Habit], name: &str, dates: Vec<String>, force: bool) {
     
-    if let Some(habit) = habits.iter_mut().find(|h| h.name == name) {
+    if let Some(habit) = habits.iter_mut().find(|h| h.is_named(name)) {
+        let dates: Vec<String> = dates.iter().map(|d| resolve_date(strip_count(d))).collect();
         
         if dates.is_empty() {
             println!("Unmarking today");
             let current_date_string = Local::now().date_naive().to_string();
             habit.history.retain(|x| x != &current_date_string);
+        } else if dates.iter().any(|d| d.contains("..")) {
+            // Ranges can wipe a lot of history at once, so ask first
+            let mut expanded = Vec::new();
+            for date in &dates {
+                if date.contains("..") {
+                    match expand_range(date) {
+                        Ok(range) => expanded.extend(range),
+                        Err(e) => {
+                            println!("{}", e);
+                            return;
+                        }
+                    }
+                } else {
+                    expanded.push(date.clone());
+                }
+            }
+
+            let count = habit.history.iter().filter(|x| expanded.contains(x)).count();
+            if count == 0 {
+                println!("Nothing to unmark.");
+                return;
+            }
+            if !force && !confirm(&format!("Unmark {} entries from {}?", count, name)) {
+                println!("Aborted.");
+                return;
+            }
+            println!("Unmarking {} entries", count);
+            habit.history.retain(|x| !expanded.contains(x));
         } else {
             println!("Unmarking: {:?}", dates);
             habit.history.retain(|x| !dates.contains(x));
         }
         
-        habit.history.sort();
+        habit.normalize();
+    } else {
+        println!("Habit not found.");
+    }
+}
+
+fn set_archived(habits: &mut [Habit], name: &str, archived: bool) {
+    if let Some(habit) = habits.iter_mut().find(|h| h.is_named(name)) {
+        habit.archived = archived;
+        if archived {
+            println!("Archived {}.", habit.name);
+        } else {
+            println!("Unarchived {}.", habit.name);
+        }
     } else {
         println!("Habit not found.");
     }
 }
 
-fn add_habit(habits: &mut Vec<Habit>, name: &str) {
+fn forget_entries(history: &mut Vec<String>, date: &str) -> bool {
+    // Match on the parsed date so entries like 2024-6-1 are caught too,
+    // falling back to the raw string for entries that don't parse at all
+    let target = NaiveDate::parse_from_str(date, "%Y-%m-%d").ok();
+    let before = history.len();
+    history.retain(|entry| match target {
+        Some(target) => NaiveDate::parse_from_str(entry, "%Y-%m-%d").ok() != Some(target),
+        None => entry != date,
+    });
+    history.len() < before
+}
+
+fn forget_date(habits: &mut [Habit], name: &str, date: &str) {
+
+    if let Some(habit) = habits.iter_mut().find(|h| h.is_named(name)) {
+
+        let target = NaiveDate::parse_from_str(date, "%Y-%m-%d").ok();
+        let label = target.map(|d| d.to_string()).unwrap_or_else(|| date.to_string());
+        if forget_entries(&mut habit.history, date) {
+            println!("Forgot {} from {}.", label, name);
+        } else {
+            println!("{} was not in {}'s history.", label, name);
+        }
+        habit.normalize();
+    } else {
+        println!("Habit not found.");
+    }
+}
+
+/// Returns false if a habit with that name already exists
+fn add_habit(habits: &mut Vec<Habit>, name: &str, frequency: Frequency, description: Option<String>, target: Option<u32>) -> bool {
+    if habits.iter().any(|h| h.is_named(name)) {
+        return false;
+    }
+
     habits.push(Habit {
-        name: name.to_string(),
+        name: name.trim().to_string(),
         streak: 0,
         history: Vec::new(),
+        frequency,
+        archived: false,
+        description,
+        target,
+        counts: BTreeMap::new(),
     });
+    true
+}
 
+fn export_csv<W: Write>(habits: &[Habit], writer: W) -> csv::Result<()> {
+    let mut writer = csv::Writer::from_writer(writer);
+    writer.write_record(["habit", "date"])?;
+    for habit in habits {
+        for date in &habit.history {
+            // DATE:N keeps the count, and import reads it back through push_dates
+            match habit.count_on(date) {
+                1 => writer.write_record([&habit.name, date])?,
+                count => writer.write_record([&habit.name, &format!("{}:{}", date, count)])?,
+            }
+        }
+    }
+    writer.flush()?;
+    Ok(())
 }
 
-fn print_graph(habits: Vec<Habit>, names: Vec<String>) {
+/// Reads habit,date rows, grouped by habit in the order they first appear
+fn read_csv(path: &Path) -> csv::Result<Vec<(String, Vec<String>)>> {
+    let mut reader = csv::Reader::from_path(path)?;
+    let mut grouped: Vec<(String, Vec<String>)> = Vec::new();
 
+    for (number, record) in reader.records().enumerate() {
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                println!("Skipping row {}: {}", number + 2, e);
+                continue;
+            }
+        };
+        let (Some(name), Some(date)) = (record.get(0), record.get(1)) else {
+            println!("Skipping row {}: expected habit,date", number + 2);
+            continue;
+        };
+        if name.trim().is_empty() {
+            println!("Skipping row {}: habit name is blank", number + 2);
+            continue;
+        }
 
-    // Merge dates
-    let mut merged: Vec<String> = Vec::new();
-    let mut habit_count = 0;
-    for name in names {
-        if let Some(habit) = habits.iter().find(|h| h.name == name) {
-            merged.extend(habit.history.iter().cloned());
-            habit_count += 1;
+        match grouped.iter_mut().find(|(n, _)| n.trim() == name.trim()) {
+            Some((_, dates)) => dates.push(date.to_string()),
+            None => grouped.push((name.to_string(), vec![date.to_string()])),
         }
     }
-    merged.sort();
+    Ok(grouped)
+}
 
-    // Count duplicates
-    let mut dates: Vec<String> = Vec::new();
-    let mut counts: Vec<i32> = Vec::new();
-    
-    let mut previous = &merged[0];
-    let mut count = 1;
+fn diff_habits(current: &[Habit], other: &[Habit]) {
+    let mut changed = false;
 
-    for i in 1..merged.len() {
-        if &merged[i] == previous {
-            count+=1;
+    for habit in current {
+        if !other.iter().any(|h| h.is_named(&habit.name)) {
+            println!("- {}", habit.name);
+            changed = true;
+        }
+    }
+
+    for habit in other {
+        if let Some(existing) = current.iter().find(|h| h.is_named(&habit.name)) {
+            let ours: BTreeSet<&String> = existing.history.iter().collect();
+            let theirs: BTreeSet<&String> = habit.history.iter().collect();
+            let added: Vec<&String> = theirs.difference(&ours).copied().collect();
+            let removed: Vec<&String> = ours.difference(&theirs).copied().collect();
+
+            if !added.is_empty() || !removed.is_empty() {
+                println!("~ {}", habit.name);
+                for date in added {
+                    println!("    + {}", date);
+                }
+                for date in removed {
+                    println!("    - {}", date);
+                }
+                changed = true;
+            }
         } else {
-            dates.push(previous.to_owned());
-            counts.push(count);
-            count = 1;
-            previous = &merged[i];
+            println!("+ {} ({} days)", habit.name, habit.history.len());
+            changed = true;
         }
+    }
 
+    if !changed {
+        println!("No differences.");
     }
+}
 
-    dates.push(previous.to_owned());
-    counts.push(count);
-    
-    // Print empty graph
-    let mut stdout = stdout();
-    let width: u16;
-    let current_date = Local::now().date_naive();
-    let current_weekday = current_date.weekday().number_from_monday();
+fn resolve_dates(dates: &[String]) -> Vec<String> {
+    if dates.is_empty() {
+        vec![Local::now().date_naive().to_string()]
+    } else {
+        let mut resolved = Vec::new();
+        for date in dates {
+            // Pin relative range ends to real dates, and leave the range itself for
+            // replay so it goes through the same checks as the original command
+            match parse_range(date) {
+                Ok((start, end)) => resolved.push(format!("{}..{}", start, end)),
+                Err(_) => resolved.push(resolve_date(date)),
+            }
+        }
+        resolved
+    }
+}
 
-    if let Some((Width(w), _)) = terminal_size() {
-       
-        stdout.execute(Clear(ClearType::All)).unwrap();
-        stdout.execute(MoveTo(0, 0)).unwrap();
-        width = w;
-        for _y in 0..7 {    
-            for _x in 0..width/2 {
-                print!(" ");
-            } print!("\n");
+fn data_changed(before: &[Habit], after: &[Habit]) -> bool {
+    before.len() != after.len()
+        || before.iter().zip(after).any(|(a, b)| a.name != b.name || a.history != b.history || a.counts != b.counts || a.archived != b.archived || a.description != b.description)
+}
+
+fn log_event(habits_path: &Path, before: &[Habit], after: &[Habit], command: &str, habit: &str, dates: &[String]) {
+    if env::var("RHABITS_EVENT_LOG").map_or(true, |v| v != "1") || !data_changed(before, after) {
+        return;
+    }
+
+    let added = after.iter().find(|h| command == "add" && h.is_named(habit));
+    let described = after.iter().find(|h| matches!(command, "add" | "describe") && h.is_named(habit));
+    let event = Event {
+        timestamp: Local::now().to_rfc3339(),
+        command: command.to_string(),
+        habit: habit.to_string(),
+        dates: dates.to_vec(),
+        frequency: added.map(|h| h.frequency),
+        target: added.and_then(|h| h.target),
+        description: described.and_then(|h| h.description.clone()),
+        snapshot: (command == "undo").then(|| after.to_vec()),
+    };
+    let line = serde_json::to_string(&event).unwrap();
+
+    let result = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(habits_path.with_file_name("events.jsonl"))
+        .and_then(|mut file| writeln!(file, "{}", line));
+    if let Err(e) = result {
+        eprintln!("Failed to write event log: {}", e);
+    }
+}
+
+fn replay_events(events_path: &Path) -> io::Result<(Vec<Habit>, usize)> {
+    let contents = fs::read_to_string(events_path)?;
+    let mut habits: Vec<Habit> = Vec::new();
+    let mut count = 0;
+
+    for (number, line) in contents.lines().enumerate() {
+        let event: Event = match serde_json::from_str(line) {
+            Ok(event) => event,
+            Err(_) => {
+                println!("Skipping malformed event on line {}", number + 1);
+                continue;
+            }
+        };
+
+        if event.command == "add" {
+            if !habits.iter().any(|h| h.is_named(&event.habit)) {
+                habits.push(Habit {
+                    name: event.habit.trim().to_string(),
+                    streak: 0,
+                    history: Vec::new(),
+                    frequency: event.frequency.unwrap_or_default(),
+                    archived: false,
+                    description: event.description,
+                    target: event.target,
+                    counts: BTreeMap::new(),
+                });
+            }
+        } else if event.command == "remove" {
+            habits.retain(|h| !h.is_named(&event.habit));
+        } else if event.command == "undo" {
+            match event.snapshot {
+                Some(restored) => habits = restored,
+                None => {
+                    println!("Skipping malformed undo on line {}", number + 1);
+                    continue;
+                }
+            }
+        } else if event.command == "purge" {
+            let name = Some(event.habit.clone()).filter(|n| !n.is_empty());
+            if let Some(before) = event.dates.first().and_then(|d| parse_date(d)) {
+                purge_before(&mut habits, name.as_deref(), before);
+            }
+        } else if let Some(habit) = habits.iter_mut().find(|h| h.is_named(&event.habit)) {
+            match event.command.as_str() {
+                "mark" => {
+                    push_dates(habit, &event.dates);
+                }
+                "unmark" => {
+                    let mut expanded = Vec::new();
+                    for date in event.dates.iter().map(|d| strip_count(d)) {
+                        match expand_range(date) {
+                            Ok(range) => expanded.extend(range),
+                            Err(_) => expanded.push(date.to_string()),
+                        }
+                    }
+                    habit.history.retain(|x| !expanded.contains(x));
+                }
+                "forget" => {
+                    for date in &event.dates {
+                        forget_entries(&mut habit.history, date);
+                    }
+                }
+                // Older logs kept the text in dates
+                "describe" => habit.description = event.description.or_else(|| event.dates.first().cloned()),
+                "archive" => habit.archived = true,
+                "unarchive" => habit.archived = false,
+                _ => {
+                    println!("Skipping unknown command on line {}", number + 1);
+                    continue;
+                }
+            }
+            habit.normalize();
+        } else {
+            // Logged before the habit's add was, so there's nothing to apply it to
+            println!("Skipping {} of unknown habit {} on line {}", event.command, event.habit, number + 1);
+            continue;
         }
-        
-        
-    } else {
-       println!("Couldn't get terminal size.");
-       std::process::exit(1);
+        count += 1;
     }
 
-    
-    
+    Ok((habits, count))
+}
+
+fn print_waybar(habits: &[Habit], name: &str) {
+    let output = match habits.iter().find(|h| h.is_named(name)) {
+        Some(habit) => {
+            let today = Local::now().date_naive().to_string();
+            serde_json::json!({
+                "text": format!("🔥 {}", habit.streak_label()),
+                "tooltip": format!("{}: {} {} streak", habit.name, habit.streak, habit.streak_unit()),
+                "class": if habit.history.contains(&today) { "done" } else { "pending" },
+            })
+        }
+        None => serde_json::json!({
+            "text": "?",
+            "tooltip": format!("{}: habit not found", name),
+            "class": "missing",
+        }),
+    };
+    println!("{}", output);
+}
+
+fn print_prompt(habits: &[Habit], zsh: bool, bash: bool) {
+    let today = Local::now().date_naive().to_string();
+    let active: Vec<&Habit> = habits.iter().filter(|h| !h.archived).collect();
+    let done = active.iter().filter(|h| h.history.contains(&today)).count();
+
+    let color = if done == active.len() {
+        "\x1b[32m"
+    } else if done > 0 {
+        "\x1b[33m"
+    } else {
+        "\x1b[31m"
+    };
+
+    // Escape codes must be marked as non-printing or the shell miscounts the prompt width
+    let escape = |code: &str| {
+        if zsh {
+            format!("%{{{}%}}", code)
+        } else if bash {
+            format!("\x01{}\x02", code)
+        } else {
+            code.to_string()
+        }
+    };
+
+    print!("{}{}/{}{}", escape(color), done, active.len(), escape("\x1b[0m"));
+}
+
+/// Intensity of each cell, one row per weekday and one column per week
+fn build_grid(counts: &BTreeMap<String, i32>, habit_count: i32, columns: i32, any: bool, start: Option<NaiveDate>, end: NaiveDate) -> Vec<Vec<f32>> {
+    let mut grid = vec![vec![0.0f32; columns as usize]; 7];
+
     // Mark completed days
-    for i in (0..=dates.len()-1).rev() {
+    for (date, &count) in counts.iter().rev() {
         
-        let date = NaiveDate::parse_from_str(&dates[i], "%Y-%m-%d").unwrap();
+        let Ok(date) = NaiveDate::parse_from_str(date, "%Y-%m-%d") else {
+            continue;
+        };
+        if date > end || start.is_some_and(|start| date < start) {
+            continue;
+        }
         let weekday = date.weekday().number_from_monday();
-        let difference = current_date-date;
-        let calc_x = 2 * (width as i32 / 2) - 2*((difference.num_days() as i32+weekday as i32-1)/7+1);
+        let difference = end-date;
+        let column = columns - ((difference.num_days() as i32+weekday as i32-1)/7+1);
         
-        if calc_x < 0 {
+        if column < 0 {
             break;
         }
+        if column >= columns {
+            continue;
+        }
         
-        let position_x = calc_x as u16;
-        let position_y = weekday as u16 -1;   
-        
-        let intensity = ((counts[i] as f32) / (habit_count as f32) * 255.0) as u8;
-        stdout.execute(MoveTo(position_x, position_y)).unwrap();
+        grid[weekday as usize - 1][column as usize] = if any {
+            1.0
+        } else {
+            (count as f32) / (habit_count as f32)
+        };
+    }
+    grid
+}
 
-        print!("\x1b[38;2;0;{};0m \x1b[0m", intensity);
+fn render_cell(intensity: f32, color: (u8, u8, u8), ascii: bool) -> String {
+    if ascii {
+        let level = (intensity * (ASCII_RAMP.len() - 1) as f32).ceil() as usize;
+        ASCII_RAMP[level.min(ASCII_RAMP.len() - 1)].to_string()
+    } else if intensity > 0.0 {
+        let (r, g, b) = color;
+        format!("\x1b[38;2;{};{};{}m \x1b[0m",
+            (r as f32 * intensity) as u8, (g as f32 * intensity) as u8, (b as f32 * intensity) as u8)
+    } else {
+        " ".to_string()
     }
-       
-    // Remove upcoming days
-    for i in current_weekday..8 {
-        stdout.execute(MoveTo(2*(width/2)-2, i as u16)).unwrap();
-        print!("  ");
+}
+
+/// Month names above the first week column that starts in that month
+fn month_labels(columns: usize, end: NaiveDate) -> String {
+    let this_week = week_start(end);
+    let mut line = " ".repeat(GRAPH_GUTTER + 2 * columns);
+    let mut free_from = 0;
+    let mut previous_month = None;
+
+    for column in 0..columns {
+        let monday = this_week - Duration::weeks((columns - 1 - column) as i64);
+        let month = monday.month();
+        let position = GRAPH_GUTTER + 2 * column;
+
+        if column > 0 && previous_month != Some(month) && position >= free_from && position + 3 <= line.len() {
+            let name = monday.format("%b").to_string();
+            line.replace_range(position..position + 3, &name);
+            free_from = position + 4;
+        }
+        previous_month = Some(month);
+    }
+    line.trim_end().to_string()
+}
+
+fn draw_grid(grid: &[Vec<f32>], color: (u8, u8, u8), ascii: bool, options: &GraphOptions, start: Option<NaiveDate>, end: NaiveDate) {
+    let current_weekday = end.weekday().number_from_monday();
+    let first_weekday = start.map_or(1, |start| start.weekday().number_from_monday());
+    let today = end == Local::now().date_naive();
+
+    println!("{}", month_labels(grid[0].len(), end));
+
+    for (row, cells) in grid.iter().enumerate() {
+        print!("{:<width$}", WEEKDAY_LABELS[row], width = GRAPH_GUTTER);
+        for (column, &intensity) in cells.iter().enumerate() {
+            let this_week = column == cells.len() - 1;
+
+            // Leave days past the end of the window, or before its start, blank
+            let cell = if (this_week && row as u32 >= current_weekday) || (column == 0 && (row as u32) < first_weekday - 1) {
+                " ".to_string()
+            } else if !options.no_weekend_shade && row >= 5 && intensity == 0.0 {
+                if ascii {
+                    ASCII_WEEKEND.to_string()
+                } else {
+                    "\x1b[48;2;40;40;40m \x1b[0m".to_string()
+                }
+            } else {
+                render_cell(intensity, color, ascii)
+            };
+
+            let gap = if !options.no_today_marker && today && this_week && row as u32 == current_weekday - 1 {
+                TODAY_MARKER
+            } else {
+                ' '
+            };
+            print!("{}{}", cell, gap);
+        }
+        println!();
+    }
+}
+
+fn draw_legend(color: (u8, u8, u8), ascii: bool) {
+    print!("{:<width$}less ", "", width = GRAPH_GUTTER);
+    for step in 0..=4 {
+        print!("{} ", render_cell(step as f32 / 4.0, color, ascii));
+    }
+    println!("more");
+}
+
+fn print_graph(habits: Vec<Habit>, names: Vec<String>, options: &GraphOptions) {
+
+
+    // Merge dates, counting how many habits were done on each. With --separate
+    // every habit gets its own counts and grid instead
+    let mut groups: Vec<(Option<String>, BTreeMap<String, i32>, i32)> = Vec::new();
+    let mut missing = Vec::new();
+    let single = options.separate || names.len() == 1;
+    for name in names {
+        if let Some(habit) = habits.iter().find(|h| h.is_named(&name) && (options.all || !h.archived)) {
+            if options.separate || groups.is_empty() {
+                groups.push((Some(habit.name.clone()), BTreeMap::new(), 0));
+            }
+            let (_, counts, habit_count) = groups.last_mut().unwrap();
+            // A date listed twice is still one completion
+            for date in habit.history.iter().collect::<BTreeSet<_>>() {
+                let done = if single { habit.count_on(date) as i32 } else { 1 };
+                *counts.entry(date.clone()).or_insert(0) += done;
+            }
+            *habit_count += 1;
+        } else if habits.iter().any(|h| h.is_named(&name)) {
+            missing.push(format!("{} is archived, pass --all to include it", name.trim()));
+        } else {
+            missing.push(format!("Habit not found: {}", name));
+        }
+    }
+    if groups.is_empty() {
+        groups.push((None, BTreeMap::new(), 0));
+    }
+
+    // Fall back to plain ASCII when stdout isn't a terminal (e.g. piped or redirected),
+    // even if stderr or stdin still are
+    let ascii = !stdout().is_terminal();
+    let detected = terminal_size_of(stdout()).map(|(Width(w), _)| w);
+    let mut width = options.width.or(detected).unwrap_or(DEFAULT_GRAPH_WIDTH);
+    if let Some(max_width) = options.max_width {
+        width = width.min(max_width);
+    }
+
+    // The window ends on --to (today by default) and, with --from, starts there
+    let end = match &options.to {
+        Some(to) => match parse_date(to) {
+            Some(date) => date,
+            None => {
+                println!("Invalid date: {}", to);
+                return;
+            }
+        },
+        None => Local::now().date_naive(),
+    };
+    let start = match &options.from {
+        Some(from) => match parse_date(from) {
+            Some(date) => Some(date),
+            None => {
+                println!("Invalid date: {}", from);
+                return;
+            }
+        },
+        None => None,
+    };
+    if start.is_some_and(|start| start > end) {
+        println!("--from must not be after --to.");
+        return;
+    }
+
+    let columns = match (start, options.weeks) {
+        (Some(start), _) => ((week_start(end) - week_start(start)).num_weeks() + 1) as i32,
+        (None, Some(weeks)) => weeks as i32,
+        (None, None) => (width.saturating_sub(GRAPH_GUTTER as u16) / 2) as i32,
+    };
+
+    let mut stdout = stdout();
+    if !ascii {
+        stdout.execute(Clear(ClearType::All)).unwrap();
+        stdout.execute(MoveTo(0, 0)).unwrap();
+    }
+
+    for (i, (label, counts, habit_count)) in groups.iter().enumerate() {
+        if options.separate {
+            if i > 0 {
+                println!();
+            }
+            println!("{}", label.as_deref().unwrap_or(""));
+        }
+        // A lone habit shades by its own busiest day rather than by how many habits were done
+        let scale = if *habit_count == 1 { counts.values().copied().max().unwrap_or(1) } else { *habit_count };
+        let grid = build_grid(counts, scale, columns, options.any, start, end);
+        draw_grid(&grid, GRAPH_COLORS[i % GRAPH_COLORS.len()], ascii, options, start, end);
+    }
+    draw_legend(GRAPH_COLORS[0], ascii);
+
+    if groups.iter().all(|(_, counts, _)| counts.is_empty()) {
+        println!("No data yet.");
+    }
+    for message in missing {
+        println!("{}", message);
     }
 
-    stdout.execute(MoveTo(0, 8)).unwrap();
     stdout.flush().unwrap();
-    stdout.execute(Hide).unwrap();
+    if !ascii {
+        stdout.execute(Hide).unwrap();
+    }
     
 }
 
-fn list_habits(habits: Vec<Habit>) {
-    // Create the table
+fn streak_sparkline(habit: &Habit, days: i64) -> String {
+    let today = Local::now().date_naive();
+    let marked: HashSet<NaiveDate> = habit.history.iter()
+        .filter_map(|entry| NaiveDate::parse_from_str(entry, "%Y-%m-%d").ok())
+        .collect();
+
+    // Replay the streak day by day, starting from the run leading into the window
+    let start = today - Duration::days(days - 1);
+    let mut run = 0;
+    let mut day = start - Duration::days(1);
+    while marked.contains(&day) {
+        run += 1;
+        day -= Duration::days(1);
+    }
+
+    let mut runs = Vec::new();
+    for offset in 0..days {
+        if marked.contains(&(start + Duration::days(offset))) {
+            run += 1;
+        } else {
+            run = 0;
+        }
+        runs.push(run);
+    }
+
+    let max = runs.iter().copied().max().unwrap_or(0);
+    runs.iter()
+        .map(|&run| SPARK_LEVELS[(run * (SPARK_LEVELS.len() - 1)).checked_div(max).unwrap_or(0)])
+        .collect()
+}
+
+fn print_stats(habits: &[Habit], name: &str) {
+    let Some(habit) = habits.iter().find(|h| h.is_named(name)) else {
+        println!("Habit not found.");
+        return;
+    };
+
+    let today = Local::now().date_naive();
+    let window_start = today - Duration::days(29);
+    let recent = habit.history.iter()
+        .filter_map(|entry| NaiveDate::parse_from_str(entry, "%Y-%m-%d").ok())
+        .filter(|date| *date >= window_start && *date <= today)
+        .count();
+
     let mut table = Table::new();
     table.add_row(Row::new(vec![
         Cell::new("Habit").with_style(Attr::Bold),
+        Cell::new(&habit.name).with_style(Attr::Bold),
+    ]));
+    table.add_row(Row::new(vec![
+        Cell::new("Total days"),
+        Cell::new(&habit.history.len().to_string()),
+    ]));
+    table.add_row(Row::new(vec![
+        Cell::new("Current streak"),
+        Cell::new(&habit.streak_label()),
+    ]));
+    table.add_row(Row::new(vec![
+        Cell::new("Longest streak"),
+        Cell::new(&habit.format_streak(habit.longest_streak())),
+    ]));
+    table.add_row(Row::new(vec![
+        Cell::new("Last 30 days"),
+        Cell::new(&format!("{:.0}%", recent as f32 / 30.0 * 100.0)),
+    ]));
+    table.printstd();
+}
+
+fn print_today(habits: &[Habit]) {
+    let today = Local::now().date_naive().to_string();
+    let (done, pending): (Vec<&Habit>, Vec<&Habit>) = habits.iter()
+        .filter(|h| !h.archived)
+        .partition(|h| h.history.contains(&today));
+
+    let mut table = Table::new();
+    table.add_row(Row::new(vec![
+        Cell::new("Pending today").with_style(Attr::Bold),
         Cell::new("Streak").with_style(Attr::Bold),
-        Cell::new("Last Entry").with_style(Attr::Bold),
     ]));
+    for habit in pending {
+        table.add_row(Row::new(vec![
+            Cell::new(&habit.name),
+            Cell::new(&habit.streak_label()),
+        ]));
+    }
+    table.printstd();
 
-    for habit in habits {
+    let mut table = Table::new();
+    table.add_row(Row::new(vec![
+        Cell::new("Done today").with_style(Attr::Bold),
+    ]));
+    for habit in done {
         table.add_row(Row::new(vec![
             Cell::new(&habit.name),
-            Cell::new(&habit.streak.to_string()),
-            Cell::new(habit.history.last().map(|s| s.as_str()).unwrap_or("")),
         ]));
     }
     table.printstd();
+}
+
+/// One cell per day ending today, blank before the habit's first entry
+fn recent_marks(habit: &Habit, days: i64) -> String {
+    let today = Local::now().date_naive();
+    let marked: HashSet<NaiveDate> = habit.history.iter()
+        .filter_map(|entry| NaiveDate::parse_from_str(entry, "%Y-%m-%d").ok())
+        .collect();
+    let Some(&first) = marked.iter().min() else {
+        return " ".repeat(days as usize);
+    };
+
+    (0..days).rev()
+        .map(|offset| today - Duration::days(offset))
+        .map(|day| if day < first {
+            ' '
+        } else if marked.contains(&day) {
+            RECENT_DONE
+        } else {
+            RECENT_MISSED
+        })
+        .collect()
+}
+
+fn list_habits(mut habits: Vec<Habit>, options: &ListOptions) {
+    let today = Local::now().date_naive().to_string();
+
+    match options.sort {
+        SortBy::Name => habits.sort(),
+        SortBy::Streak => habits.sort_by(|a, b| b.streak.cmp(&a.streak).then_with(|| a.name.cmp(&b.name))),
+        SortBy::Recent => habits.sort_by(|a, b| b.history.last().cmp(&a.history.last()).then_with(|| a.name.cmp(&b.name))),
+    }
+
+    // Create the table
+    let mut table = Table::new();
+    let mut header = vec![
+        Cell::new("Habit").with_style(Attr::Bold),
+        Cell::new("Streak").with_style(Attr::Bold),
+        Cell::new("Last Entry").with_style(Attr::Bold),
+    ];
+    if options.days > 0 {
+        header.push(Cell::new(&format!("Last {}d", options.days)).with_style(Attr::Bold));
+    }
+    // Only show descriptions once some habit has one, to keep the simple case clean
+    let descriptions = habits.iter().any(|h| h.description.is_some());
+    if descriptions {
+        header.push(Cell::new("Description").with_style(Attr::Bold));
+    }
+    if options.sparkline_streak {
+        header.push(Cell::new("Trend").with_style(Attr::Bold));
+    }
+    table.add_row(Row::new(header));
+
+    for habit in habits.iter().filter(|h| options.all || !h.archived) {
+        // Tick off habits already done today, so they stand apart from long streaks
+        let name = if habit.history.contains(&today) {
+            format!("{} ✓", habit.name)
+        } else {
+            habit.name.clone()
+        };
+        let streak_color = match habit.streak {
+            0 => color::RED,
+            1..=6 => color::YELLOW,
+            _ => color::GREEN,
+        };
+
+        let mut row = vec![
+            Cell::new(&name),
+            Cell::new(&habit.streak_label()).with_style(Attr::ForegroundColor(streak_color)),
+            Cell::new(habit.history.last().map(|s| s.as_str()).unwrap_or("")),
+        ];
+        if options.days > 0 {
+            row.push(Cell::new(&recent_marks(habit, options.days as i64)));
+        }
+        if descriptions {
+            row.push(Cell::new(habit.description.as_deref().unwrap_or("")));
+        }
+        if options.sparkline_streak {
+            row.push(Cell::new(&streak_sparkline(habit, SPARKLINE_DAYS)));
+        }
+        table.add_row(Row::new(row));
+    }
+    table.printstd();
 
 
     
@@ -303,46 +1570,366 @@ fn main() {
     
     let cli = Cli::parse();
 
-    let habits_path = get_habits_path().unwrap();
-    let mut habits = load_data(&habits_path).expect("Failed to load data");
+    let habits_path = get_habits_path(cli.data_dir.clone()).unwrap();
+    let mut habits = match load_data(&habits_path) {
+        Ok(habits) => habits,
+        Err(e) => {
+            eprintln!("Couldn't read {}: {}", habits_path.display(), e);
+            std::process::exit(1);
+        }
+    };
+    let before = habits.clone();
 
     match &cli.command {
-        Commands::List => {
+        Commands::List { options } => {
             check_streak(&mut habits);
             let _ = save_data(&habits_path, &habits);
-            list_habits(habits);
+            list_habits(habits, options);
+        }
+        Commands::Graph { names, options } => {
+            print_graph(habits, names.to_vec(), options);
         }
-        Commands::Graph { names } => {
-            print_graph(habits, names.to_vec());
+        Commands::Mark { name, dates, quiet_if_exists } => {
+            // Recompute first, so a stale stored streak can't fake a milestone crossing
+            check_streak(&mut habits);
+            let previous = habits.iter().find(|h| h.is_named(name)).map(|h| h.streak);
+
+            let (accepted, rejected) = mark_habit(&mut habits, name, dates.to_vec(), *quiet_if_exists);
+            if rejected > 0 {
+                println!("Marked {} dates, skipped {} malformed.", accepted, rejected);
+            }
+            check_streak(&mut habits);
+            if let (Some(previous), Some(habit)) = (previous, habits.iter().find(|h| h.is_named(name))) {
+                announce_milestones(habit, previous);
+            }
+            let _ = save_data(&habits_path, &habits);
+            log_event(&habits_path, &before, &habits, "mark", name, &resolve_dates(dates));
         }
-        Commands::Mark { name, dates} => {
-            mark_habit(&mut habits, name, dates.to_vec());
+        Commands::Unmark { name, dates, force } => {
+            unmark_habit(&mut habits, name, dates.to_vec(), *force);
             check_streak(&mut habits);
             let _ = save_data(&habits_path, &habits);
+            log_event(&habits_path, &before, &habits, "unmark", name, &resolve_dates(dates));
+        }
+        Commands::Add { names, times_per_week, desc, target } => {
+            let frequency = times_per_week.map_or(Frequency::Daily, Frequency::TimesPerWeek);
+            let mut added = Vec::new();
+            for name in names {
+                if name.trim().is_empty() {
+                    println!("Habit names can't be blank.");
+                    continue;
+                }
+                if add_habit(&mut habits, name, frequency, desc.clone(), *target) {
+                    println!("Added {}", name.trim());
+                    added.push(name.trim());
+                } else {
+                    println!("Habit already exists: {}", name.trim());
+                }
+            }
+            let _ = save_data(&habits_path, &habits);
+            for name in added {
+                log_event(&habits_path, &before, &habits, "add", name, &[]);
+            }
+        }
+        Commands::Describe { name, text } => {
+            if let Some(habit) = habits.iter_mut().find(|h| h.is_named(name)) {
+                habit.description = Some(text.clone());
+                println!("Updated the description of {}.", habit.name);
+            } else {
+                println!("Habit not found.");
+            }
+            let _ = save_data(&habits_path, &habits);
+            log_event(&habits_path, &before, &habits, "describe", name, &[]);
         }
-        Commands::Unmark { name, dates} => {
-            unmark_habit(&mut habits, name, dates.to_vec());
+        Commands::Remove { name, force } => {
+            let Some(habit) = habits.iter().find(|h| h.is_named(name)) else {
+                println!("Habit not found.");
+                return;
+            };
+            let prompt = format!("Remove {} and its {} entries?", habit.name, habit.history.len());
+            if !force && !confirm(&prompt) {
+                println!("Aborted.");
+                return;
+            }
+            habits.retain(|h| !h.is_named(name));
+            let _ = save_data(&habits_path, &habits);
+            log_event(&habits_path, &before, &habits, "remove", name, &[]);
+        }
+        Commands::Today => {
+            check_streak(&mut habits);
+            print_today(&habits);
+        }
+        Commands::Stats { name } => {
+            check_streak(&mut habits);
+            print_stats(&habits, name);
+        }
+        Commands::Purge { name, before: cutoff, force } => {
+            let Some(date) = parse_date(cutoff) else {
+                println!("Invalid date: {}", cutoff);
+                return;
+            };
+
+            let removed = purge_before(&mut habits, name.as_deref(), date);
+            if removed.is_empty() {
+                println!("Habit not found.");
+                return;
+            }
+            let total: usize = removed.iter().map(|(_, count)| count).sum();
+            let prompt = format!("Purge {} entries before {}?", total, date);
+            if total > 0 && !force && !confirm(&prompt) {
+                println!("Aborted.");
+                return;
+            }
+            for (habit, count) in removed {
+                println!("{}: removed {} entries", habit, count);
+            }
             check_streak(&mut habits);
             let _ = save_data(&habits_path, &habits);
+            log_event(&habits_path, &before, &habits, "purge", name.as_deref().unwrap_or(""), &[date.to_string()]);
         }
-        Commands::Add { name } => {
-            add_habit(&mut habits, name);
+        Commands::Archive { name } => {
+            set_archived(&mut habits, name, true);
             let _ = save_data(&habits_path, &habits);
+            log_event(&habits_path, &before, &habits, "archive", name, &[]);
         }
-        Commands::Remove { name } => {
-            habits.retain(|h| h.name != *name);
+        Commands::Unarchive { name } => {
+            set_archived(&mut habits, name, false);
             let _ = save_data(&habits_path, &habits);
+            log_event(&habits_path, &before, &habits, "unarchive", name, &[]);
+        }
+        Commands::Recompute => {
+            check_streak(&mut habits);
+            let _ = save_data(&habits_path, &habits);
+            println!("Recomputed streaks for {} habits.", habits.len());
+        }
+        Commands::Forget { name, date } => {
+            forget_date(&mut habits, name, date);
+            check_streak(&mut habits);
+            let _ = save_data(&habits_path, &habits);
+            log_event(&habits_path, &before, &habits, "forget", name, std::slice::from_ref(date));
+        }
+        Commands::Replay { force } => {
+            let events_path = habits_path.with_file_name("events.jsonl");
+            match replay_events(&events_path) {
+                Ok((replayed, count)) => {
+                    let prompt = format!("Replace {} habits with {} rebuilt from {} events?", habits.len(), replayed.len(), count);
+                    if !force && !confirm(&prompt) {
+                        println!("Aborted.");
+                        return;
+                    }
+                    habits = replayed;
+                    check_streak(&mut habits);
+                    let _ = save_data(&habits_path, &habits);
+                    println!("Replayed {} events.", count);
+                }
+                Err(e) => println!("Couldn't read {}: {}", events_path.display(), e),
+            }
+        }
+        Commands::Undo => {
+            let backup_path = habits_path.with_extension("json.bak");
+            if !backup_path.exists() {
+                println!("Nothing to undo.");
+                return;
+            }
+            match fs::rename(&backup_path, &habits_path) {
+                Ok(()) => {
+                    println!("Restored the data from before the last change.");
+                    // The event carries the restored habits, so replay lands on the same state
+                    // no matter which command made the change
+                    let restored = load_data(&habits_path).expect("Failed to load data");
+                    log_event(&habits_path, &before, &restored, "undo", "", &[]);
+                }
+                Err(e) => println!("Couldn't restore {}: {}", backup_path.display(), e),
+            }
+        }
+        Commands::Completions { shell } => {
+            clap_complete::generate(*shell, &mut Cli::command(), "rhabits", &mut io::stdout());
+        }
+        Commands::Waybar { name } => {
+            check_streak(&mut habits);
+            print_waybar(&habits, name);
+        }
+        Commands::Prompt { zsh, bash } => {
+            print_prompt(&habits, *zsh, *bash);
+        }
+        Commands::Export { path } => {
+            let result = match path {
+                Some(path) => fs::File::create(path)
+                    .map_err(csv::Error::from)
+                    .and_then(|file| export_csv(&habits, file)),
+                None => export_csv(&habits, io::stdout()),
+            };
+            if let Err(e) = result {
+                println!("Export failed: {}", e);
+            }
+        }
+        Commands::Import { path } => {
+            let grouped = match read_csv(path) {
+                Ok(grouped) => grouped,
+                Err(e) => {
+                    println!("Couldn't read {}: {}", path.display(), e);
+                    return;
+                }
+            };
+
+            for (name, dates) in &grouped {
+                add_habit(&mut habits, name, Frequency::Daily, None, None);
+                let habit = habits.iter_mut().find(|h| h.is_named(name)).unwrap();
+                let (accepted, rejected) = push_dates(habit, dates);
+                habit.normalize();
+                println!("{}: imported {} dates, skipped {}", habit.name, accepted, rejected);
+            }
+            check_streak(&mut habits);
+            let _ = save_data(&habits_path, &habits);
+
+            for (name, dates) in &grouped {
+                if !before.iter().any(|h| h.is_named(name)) {
+                    log_event(&habits_path, &before, &habits, "add", name, &[]);
+                }
+                log_event(&habits_path, &before, &habits, "mark", name, &resolve_dates(dates));
+            }
+        }
+        Commands::Diff { path } => {
+            if !path.exists() {
+                println!("File not found.");
+                return;
+            }
+            // Parse directly rather than through load_data, so a bad file gets an error
+            // instead of a .corrupt copy and a diff against nothing
+            let contents = match fs::read_to_string(path) {
+                Ok(contents) => contents,
+                Err(e) => {
+                    println!("Couldn't read {}: {}", path.display(), e);
+                    return;
+                }
+            };
+            let mut other = match parse_data(&contents) {
+                Ok(data) => data.habits,
+                Err(e) => {
+                    println!("Couldn't parse {}: {}", path.display(), e);
+                    return;
+                }
+            };
+            for habit in &mut other {
+                habit.normalize();
+            }
+            diff_habits(&habits, &other);
         }
-        
-        
     }
     
 }
 
 /* To-do
-- Support adding multiple habits at once
-- Add failsafe for malformed dates
 - Add default habit
-- Multiple habits graphing
-- Waybar module
- */
\ No newline at end of file
+ */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(input: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(input, "%Y-%m-%d").unwrap()
+    }
+
+    fn history(dates: &[&str]) -> Vec<String> {
+        dates.iter().map(|d| d.to_string()).collect()
+    }
+
+    fn habit(dates: &[&str]) -> Habit {
+        Habit {
+            name: "reading".to_string(),
+            streak: 0,
+            history: history(dates),
+            frequency: Frequency::Daily,
+            archived: false,
+            description: None,
+            target: None,
+            counts: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn duplicated_dates_count_once() {
+        let today = date("2024-05-11");
+        let dates = history(&["2024-05-11", "2024-05-11", "2024-05-10"]);
+        assert_eq!(current_streak(&dates, today), 2);
+    }
+
+    #[test]
+    fn streak_stops_at_the_first_gap() {
+        let dates = history(&["2024-01-01", "2024-01-02", "2024-05-10", "2024-05-11"]);
+        assert_eq!(current_streak(&dates, date("2024-05-11")), 2);
+        assert_eq!(current_streak(&dates, date("2024-05-12")), 2);
+        assert_eq!(current_streak(&dates, date("2024-05-13")), 0);
+        assert_eq!(current_streak(&dates, date("2024-06-01")), 0);
+    }
+
+    #[test]
+    fn single_day_streak() {
+        let dates = history(&["2024-05-11"]);
+        assert_eq!(current_streak(&dates, date("2024-05-11")), 1);
+        assert_eq!(current_streak(&dates, date("2024-05-12")), 1);
+        assert_eq!(current_streak(&dates, date("2024-05-13")), 0);
+    }
+
+    #[test]
+    fn empty_history_has_no_streak() {
+        assert_eq!(current_streak(&[], date("2024-05-11")), 0);
+    }
+
+    #[test]
+    fn longest_weekly_streak_counts_kept_weeks() {
+        // Weeks of Jan 1 and Jan 8 kept, Jan 15 short, then Jan 22, 29 and Feb 5 kept
+        let dates = history(&[
+            "2024-01-01", "2024-01-03", "2024-01-08", "2024-01-09", "2024-01-15",
+            "2024-01-22", "2024-01-23", "2024-01-29", "2024-01-30", "2024-02-05", "2024-02-06",
+        ]);
+        assert_eq!(longest_weekly_streak(&dates, 2), 3);
+        assert_eq!(longest_weekly_streak(&dates, 1), 6);
+        assert_eq!(longest_weekly_streak(&[], 2), 0);
+    }
+
+    #[test]
+    fn legacy_array_round_trips() {
+        let dir = env::temp_dir().join(format!("rhabits-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("habits.json");
+        fs::write(&path, r#"[
+            {"name": "reading", "streak": 2, "history": ["2024-05-10", "2024-05-11"]},
+            {"name": "gym", "streak": 0, "history": [], "frequency": {"TimesPerWeek": 3}, "archived": true}
+        ]"#).unwrap();
+
+        let habits = load_data(&path).unwrap();
+        assert_eq!(habits.len(), 2);
+        assert_eq!(habits[0].history, history(&["2024-05-10", "2024-05-11"]));
+        assert_eq!(habits[0].frequency, Frequency::Daily);
+        assert!(!habits[0].archived);
+        assert_eq!(habits[1].frequency, Frequency::TimesPerWeek(3));
+
+        save_data(&path, &habits).unwrap();
+        let saved: serde_json::Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved["version"], DATA_VERSION);
+        assert!(saved["habits"].is_array());
+        assert_eq!(load_data(&path).unwrap(), habits);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn huge_offsets_are_invalid_dates() {
+        assert_eq!(parse_date("-999999999999999"), None);
+        assert_eq!(parse_date("+999999999999999"), None);
+    }
+
+    #[test]
+    fn check_streak_ignores_duplicates() {
+        let today = Local::now().date_naive();
+        let yesterday = (today - Duration::days(1)).to_string();
+        let today = today.to_string();
+        let mut habits = vec![habit(&[&today, &today, &yesterday])];
+
+        check_streak(&mut habits);
+        assert_eq!(habits[0].streak, 2);
+        assert_eq!(habits[0].history, vec![yesterday, today]);
+    }
+}